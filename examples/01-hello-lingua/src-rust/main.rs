@@ -18,8 +18,8 @@ struct RustGreeting {
 }
 
 extern "C" {
-	fn ask_luau_to_say_hello() -> u32;
-	fn respond_to_luau_greeting(response: u32);
+	fn ask_luau_to_say_hello() -> u64;
+	fn respond_to_luau_greeting(response: u64);
 }
 
 fn main() {
@@ -1,18 +1,23 @@
-use lingua::{receive_from_luau, send_to_luau};
+use lingua::{receive_from_luau, send_result_to_luau, ExternError};
 
 mod panic_handler;
 
 #[no_mangle]
 pub extern "C" fn calculate_fridge_value(
-	prices: u32,
-	fridge: u32
-) -> u32 {
+	prices: u64,
+	fridge: u64,
+	out_error: *mut ExternError
+) -> u64 {
 	panic_handler::connect();
 
-	let result = super::calculate_fridge_value(
-		receive_from_luau(prices.into()).unwrap(), 
-		receive_from_luau(fridge.into()).unwrap()
-	);
-
-	send_to_luau(&result).unwrap().into()
+	// SAFETY: `out_error` is the out-parameter of this `extern "C"` function,
+	// which the Luau side is expected to always pass a valid pointer for.
+	unsafe {
+		send_result_to_luau(out_error, || {
+			super::calculate_fridge_value(
+				receive_from_luau(prices.into()).unwrap(),
+				receive_from_luau(fridge.into()).unwrap()
+			)
+		})
+	}
 }
\ No newline at end of file
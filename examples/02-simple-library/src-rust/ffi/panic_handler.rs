@@ -1,20 +1,58 @@
-// This forwards on any panics to the Luau side, where they can become visible
-// in the output.
+// This forwards on any panics to the Luau side, where they can become
+// visible in the output. The whole report is sent as a single structured
+// payload - a file/line/column plus the panic message - in one FFI call,
+// rather than as unstructured text trickled across the boundary one byte at
+// a time.
 
-use std::panic;
+use std::panic::{self, Location};
+
+use serde::Serialize;
+
+use lingua::try_send_to_luau;
 
 extern "C" {
-	fn panic_reporter(
-		len_or_byte: u32
-	);
+	fn panic_reporter(report_handle: u64);
+}
+
+#[derive(Serialize)]
+struct PanicReport {
+	message: String,
+	location: Option<PanicLocation>
+}
+
+#[derive(Serialize)]
+struct PanicLocation {
+	file: String,
+	line: u32,
+	column: u32
+}
+
+impl From<&Location<'_>> for PanicLocation {
+	fn from(
+		location: &Location<'_>
+	) -> Self {
+		Self {
+			file: location.file().to_string(),
+			line: location.line(),
+			column: location.column()
+		}
+	}
 }
 
 pub fn connect() {
-	panic::set_hook(
-		Box::new(|panic| {
-			let foo = format!("{panic}");
-			unsafe { panic_reporter(foo.len() as u32); }
-			foo.bytes().for_each(|byte| unsafe { panic_reporter(byte as u32); });
-		})
-	);
-}
\ No newline at end of file
+	panic::set_hook(Box::new(|panic| {
+		let report = PanicReport {
+			message: panic.payload_as_str()
+				.map(str::to_string)
+				.unwrap_or_else(|| panic.to_string()),
+			location: panic.location().map(PanicLocation::from)
+		};
+		// Best-effort: if even sending the panic report fails - or this panic
+		// happened while another call into lingua was already on the stack,
+		// e.g. one of its own `API_STATE.with_borrow_mut` calls unwinding -
+		// there's nowhere left to report that failure to, so it's dropped.
+		if let Some(Ok(handle)) = try_send_to_luau(&report) {
+			unsafe { panic_reporter(handle.into()); }
+		}
+	}));
+}
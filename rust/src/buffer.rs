@@ -0,0 +1,13 @@
+// A C-compatible description of a `Vec<u8>`'s raw parts, used to hand a byte
+// buffer across the FFI boundary without forcing it through a
+// length-prefixed or null-terminated encoding - unlike a `String`, arbitrary
+// bytes can't be trusted to stop at the first zero or to be valid UTF-8.
+
+/// The raw parts of a `Vec<u8>`, laid out so it can be read from Luau without
+/// Rust having to decode anything first.
+#[repr(C)]
+pub struct RustBuffer {
+	pub ptr: *mut u8,
+	pub len: u32,
+	pub capacity: u32
+}
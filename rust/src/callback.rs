@@ -0,0 +1,143 @@
+// Lingua otherwise only supports calling Rust from Luau with an immediate
+// return; this lets Rust hold onto a Luau function and invoke it later, for
+// event handlers, iterators, or completion notifications. Modelled on the
+// callback-interface support in uniffi_core.
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use std::panic::AssertUnwindSafe;
+
+use crate::{
+	handle_map::{self, StaleHandleError},
+	receive_from_luau, send_to_luau,
+	ApiState, ReceiveFromLuauError, SendToLuauError, API_STATE, ffi_panic_boundary
+};
+
+extern "C" {
+	/// Implemented by the Luau side. Invokes the Luau function referred to by
+	/// `callback_handle` with the arguments behind `args_handle` (sent via
+	/// the usual `send_to_luau` convention) and returns a handle to the
+	/// result, to be read back with `receive_from_luau`.
+	fn lingua_invoke_luau_callback(
+		callback_handle: u64,
+		args_handle: u64
+	) -> u64;
+
+	/// Implemented by the Luau side. Called once the Rust side has dropped
+	/// the `LuauCallback` referring to `luau_callback_ref`, so Luau knows it
+	/// can release whatever it's holding to keep that function alive.
+	fn lingua_release_luau_callback_ref(
+		luau_callback_ref: u64
+	);
+}
+
+/// Removes `callback_handle` from `luau_callbacks`, if it's still live,
+/// returning the `luau_callback_ref` that was stored behind it. Shared by
+/// `lingua_deregister_luau_callback` and `LuauCallback`'s `Drop` impl so a
+/// callback is only ever removed from the map once, however its teardown was
+/// triggered.
+fn remove_luau_callback(
+	callback_handle: u64
+) -> Option<u64> {
+	let handle = handle_map::handle_from_bits(callback_handle)?;
+	API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+		api_state.luau_callbacks.remove(handle).ok()
+	})
+}
+
+/// The Luau side calls this to hand Rust a reference to one of its functions.
+/// `luau_callback_ref` is an opaque, Luau-defined token identifying that
+/// function - Lingua doesn't interpret it, just stores it and hands it back
+/// to `lingua_invoke_luau_callback` later. The returned handle is what should
+/// be sent to whichever Rust function expects a [`LuauCallback`].
+#[no_mangle]
+extern "C" fn lingua_register_luau_callback(
+	luau_callback_ref: u64
+) -> u64 {
+	let mut handle_bits = 0;
+	ffi_panic_boundary(AssertUnwindSafe(|| {
+		handle_bits = API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+			api_state.luau_callbacks.insert(luau_callback_ref).into()
+		});
+	}));
+	handle_bits
+}
+
+/// The Luau side calls this once it no longer intends to keep a callback
+/// alive - in the usual case, because the `LuauCallback` that referred to it
+/// on the Rust side has already been dropped and deregistered itself. Calling
+/// this with a handle that's already gone is a harmless no-op.
+#[no_mangle]
+extern "C" fn lingua_deregister_luau_callback(
+	callback_handle: u64
+) -> u8 {
+	ffi_panic_boundary(|| {
+		remove_luau_callback(callback_handle);
+	}) as u8
+}
+
+/// A handle to a Luau function that Rust code can keep and invoke later.
+/// Obtained by converting the `u64` handle that
+/// `lingua_register_luau_callback` returned - the Luau side is expected to
+/// have registered its function before handing that `u64` to any Rust
+/// function that accepts a `LuauCallback`.
+#[repr(transparent)]
+pub struct LuauCallback(u64);
+
+impl From<u64> for LuauCallback {
+	fn from(
+		value: u64
+	) -> Self {
+		Self(value)
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum InvokeLuauCallbackError {
+	#[error("callback handle does not refer to a live callback: {0}")]
+	StaleHandle(StaleHandleError),
+	#[error("error while sending arguments to luau")]
+	SendError(SendToLuauError),
+	#[error("error while receiving the callback's result from luau")]
+	ReceiveError(ReceiveFromLuauError)
+}
+
+impl LuauCallback {
+	/// Invokes the Luau function this handle refers to, sending `args` as
+	/// its arguments and decoding its return value as `R`.
+	pub fn invoke<A: Serialize, R: DeserializeOwned>(
+		&self,
+		args: &A
+	) -> Result<R, InvokeLuauCallbackError> {
+		let luau_callback_ref = self.luau_callback_ref()?;
+		let args_handle = send_to_luau(args).map_err(InvokeLuauCallbackError::SendError)?;
+		let result_handle = unsafe {
+			lingua_invoke_luau_callback(luau_callback_ref, args_handle.into())
+		};
+		receive_from_luau(result_handle.into()).map_err(InvokeLuauCallbackError::ReceiveError)
+	}
+
+	fn luau_callback_ref(
+		&self
+	) -> Result<u64, InvokeLuauCallbackError> {
+		let handle = handle_map::handle_from_bits(self.0)
+			.ok_or(InvokeLuauCallbackError::StaleHandle(StaleHandleError::NotOccupied))?;
+		API_STATE.with_borrow(|api_state: &ApiState| {
+			api_state.luau_callbacks.get(handle).copied()
+				.map_err(InvokeLuauCallbackError::StaleHandle)
+		})
+	}
+}
+
+impl Drop for LuauCallback {
+	fn drop(
+		&mut self
+	) {
+		if let Some(luau_callback_ref) = remove_luau_callback(self.0) {
+			// SAFETY: `lingua_release_luau_callback_ref` takes no borrowed
+			// data, just an opaque token Luau already owns the meaning of.
+			unsafe { lingua_release_luau_callback_ref(luau_callback_ref); }
+		}
+	}
+}
@@ -0,0 +1,104 @@
+// The wire format used to turn Rust values into bytes (and back) before they
+// cross the FFI boundary. Plain JSON is the default and has no extra
+// dependencies, but it round-trips through a human-readable string even for
+// payloads that are really just numbers or binary blobs. Swapping in a
+// compact binary codec is a matter of enabling a Cargo feature - both sides
+// of a given build must still agree on which one is active.
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EncodeError {
+	#[cfg(feature = "codec-json")]
+	#[error("error while encoding as json")]
+	Json(serde_json::Error),
+	#[cfg(feature = "codec-postcard")]
+	#[error("error while encoding as postcard")]
+	Postcard(postcard::Error)
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+	#[cfg(feature = "codec-json")]
+	#[error("error while decoding from json")]
+	Json(serde_json::Error),
+	#[cfg(feature = "codec-postcard")]
+	#[error("error while decoding from postcard")]
+	Postcard(postcard::Error)
+}
+
+/// A pluggable wire format for data sent across the FFI boundary. The active
+/// codec is chosen at compile time via Cargo features so both sides of a
+/// build always agree on the format - see [`ActiveCodec`].
+pub trait Codec {
+	fn encode<S: Serialize>(
+		data: &S
+	) -> Result<Vec<u8>, EncodeError>;
+
+	fn decode<D: DeserializeOwned>(
+		bytes: &[u8]
+	) -> Result<D, DecodeError>;
+}
+
+/// The default codec: plain JSON, via `serde_json`. Human-readable and
+/// dependency-light, at the cost of payload size and a UTF-8 round trip for
+/// data that doesn't need one.
+#[cfg(feature = "codec-json")]
+pub struct JsonCodec;
+
+#[cfg(feature = "codec-json")]
+impl Codec for JsonCodec {
+	fn encode<S: Serialize>(
+		data: &S
+	) -> Result<Vec<u8>, EncodeError> {
+		serde_json::to_vec(data).map_err(EncodeError::Json)
+	}
+
+	fn decode<D: DeserializeOwned>(
+		bytes: &[u8]
+	) -> Result<D, DecodeError> {
+		serde_json::from_slice(bytes).map_err(DecodeError::Json)
+	}
+}
+
+/// A compact binary codec, via `postcard`. Smaller payloads and no UTF-8
+/// round trip, at the cost of human-readability on the wire.
+#[cfg(feature = "codec-postcard")]
+pub struct PostcardCodec;
+
+#[cfg(feature = "codec-postcard")]
+impl Codec for PostcardCodec {
+	fn encode<S: Serialize>(
+		data: &S
+	) -> Result<Vec<u8>, EncodeError> {
+		postcard::to_allocvec(data).map_err(EncodeError::Postcard)
+	}
+
+	fn decode<D: DeserializeOwned>(
+		bytes: &[u8]
+	) -> Result<D, DecodeError> {
+		postcard::from_bytes(bytes).map_err(DecodeError::Postcard)
+	}
+}
+
+// Both sides of a Lingua integration need to agree on the wire format, so
+// exactly one codec feature must be active - never zero, and never both (the
+// latter would leave one codec compiled in but never reachable through
+// `ActiveCodec`, which is its own source of confusion).
+#[cfg(all(feature = "codec-json", feature = "codec-postcard"))]
+compile_error!(
+	"Only one of Lingua's \"codec-json\" and \"codec-postcard\" features can \
+	be enabled at a time. To use postcard, disable default features first: \
+	`default-features = false, features = [\"codec-postcard\"]`.");
+
+#[cfg(not(any(feature = "codec-json", feature = "codec-postcard")))]
+compile_error!(
+	"Lingua needs exactly one codec feature enabled - \"codec-json\" (the \
+	default) or \"codec-postcard\".");
+
+#[cfg(feature = "codec-json")]
+pub type ActiveCodec = JsonCodec;
+
+#[cfg(feature = "codec-postcard")]
+pub type ActiveCodec = PostcardCodec;
@@ -4,6 +4,12 @@ pub struct StringAllocs {
 	ptr_map: HashMap<*mut u8, ManuallyDrop<String>>
 }
 
+impl Default for StringAllocs {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl StringAllocs {
 	pub fn new() -> Self {
 		Self {
@@ -25,7 +31,7 @@ impl StringAllocs {
 		&mut self,
 		ptr: *mut u8
 	) -> Option<&str> {
-		let Some(str) = self.ptr_map.get(&ptr) else {return None};
+		let str = self.ptr_map.get(&ptr)?;
 		Some(str)
 	}
 
@@ -33,7 +39,7 @@ impl StringAllocs {
 		&mut self,
 		ptr: *mut u8
 	) -> Option<&mut str> {
-		let Some(str) = self.ptr_map.get_mut(&ptr) else {return None};
+		let str = self.ptr_map.get_mut(&ptr)?;
 		Some(str)
 	}
 
@@ -85,7 +91,7 @@ extern "C" fn lingua_dealloc_foreign_string(
 #[no_mangle]
 extern "C" fn lingua_dealloc_received_string(
 	ptr: *mut u8
-) -> () {
+) {
 	unsafe {
 		drop(CString::from_raw(ptr as *mut i8));
 	}
@@ -0,0 +1,158 @@
+// Lets the Rust side expose an async computation to Luau as a handle that
+// gets polled to completion, rather than blocking the FFI call until the
+// whole thing is done. The WASM host - not Rust - owns the event loop, so
+// Luau is the one driving: it calls `lingua_poll_future` repeatedly until the
+// future settles, rather than Rust waking anything up on its own. Modelled on
+// the foreign-future / rust-future split in uniffi_core.
+
+use std::{
+	future::Future,
+	panic::AssertUnwindSafe,
+	pin::Pin,
+	task::{Context, Poll, RawWaker, RawWakerVTable, Waker}
+};
+
+use serde::Serialize;
+
+use crate::{
+	codec::{self, Codec, EncodeError},
+	handle_map::{self, Handle},
+	send_bytes_to_luau, ffi_panic_boundary,
+	ApiState, API_STATE
+};
+
+pub(crate) type BoxedFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, EncodeError>>>>;
+
+/// An opaque handle to a Rust future that's been exposed to Luau. Returned by
+/// [`send_future_to_luau`]; the Luau side is expected to poll it via
+/// `lingua_poll_future` until it resolves (or cancel it early via
+/// `lingua_cancel_future`).
+#[repr(transparent)]
+pub struct FutureHandle(Handle);
+impl From<FutureHandle> for u64 {
+	fn from(
+		value: FutureHandle
+	) -> Self {
+		value.0.into()
+	}
+}
+
+/// Exposes `f` to the Luau side as a pollable handle. The future's output is
+/// encoded with the active [`Codec`] as soon as it resolves, so polling never
+/// has to hand a generic value back across the FFI boundary - only bytes.
+pub fn send_future_to_luau<F>(
+	f: F
+) -> FutureHandle
+where
+	F: Future + 'static,
+	F::Output: Serialize
+{
+	let boxed: BoxedFuture = Box::pin(async move {
+		let output = f.await;
+		codec::ActiveCodec::encode(&output)
+	});
+	API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+		FutureHandle(api_state.futures.insert(boxed))
+	})
+}
+
+/// No single-threaded WASM host actually wakes a future asynchronously -
+/// Luau is expected to just poll again later - so waking is a no-op and the
+/// waker carries no state at all.
+fn noop_waker() -> Waker {
+	fn no_op(_: *const ()) {}
+	fn clone(_: *const ()) -> RawWaker {
+		raw_waker()
+	}
+	fn raw_waker() -> RawWaker {
+		const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+		RawWaker::new(std::ptr::null(), &VTABLE)
+	}
+	// SAFETY: the vtable's functions all ignore the data pointer, so handing
+	// out a null one is sound - there's nothing behind it to dereference.
+	unsafe { Waker::from_raw(raw_waker()) }
+}
+
+#[repr(u8)]
+enum PollStatus {
+	Pending = 0,
+	Ready = 1,
+	Error = 2
+}
+
+fn poll_future(
+	handle_bits: u64
+) -> PollStatus {
+	let Some(handle) = handle_map::handle_from_bits(handle_bits) else {
+		return PollStatus::Error;
+	};
+	// The future is taken out of `API_STATE` - rather than polled while
+	// still borrowed from it - because polling can run arbitrary Rust code
+	// that calls back into Lingua (invoking a `LuauCallback`, sending or
+	// receiving data, spawning another future). Polling under the borrow
+	// would make any of that re-borrow `API_STATE` while it's already
+	// mutably borrowed here and panic.
+	let Ok(mut future) = API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+		api_state.futures.take(handle)
+	}) else {
+		return PollStatus::Error;
+	};
+	let waker = noop_waker();
+	let mut context = Context::from_waker(&waker);
+	let poll = future.as_mut().poll(&mut context);
+	match poll {
+		Poll::Pending => {
+			API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+				api_state.futures.put_back(handle, future);
+			});
+			PollStatus::Pending
+		},
+		Poll::Ready(Err(_)) => {
+			API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+				api_state.futures.release(handle);
+			});
+			PollStatus::Error
+		},
+		Poll::Ready(Ok(bytes)) => {
+			API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+				api_state.futures.release(handle);
+			});
+			match send_bytes_to_luau(bytes) {
+				Ok(_) => PollStatus::Ready,
+				Err(_) => PollStatus::Error
+			}
+		}
+	}
+}
+
+/// The Luau side calls this to drive a future forward. Returns `Pending` if
+/// the future hasn't settled yet (call again later), `Ready` once its output
+/// has been sent to Luau via the usual `send_to_luau` FFI call, or `Error` if
+/// the handle was stale, the future panicked, or its output couldn't be
+/// encoded.
+#[no_mangle]
+extern "C" fn lingua_poll_future(
+	handle_bits: u64
+) -> u8 {
+	let mut status = PollStatus::Error;
+	ffi_panic_boundary(AssertUnwindSafe(|| {
+		status = poll_future(handle_bits);
+	}));
+	status as u8
+}
+
+/// The Luau side calls this to abandon a future before it resolves, dropping
+/// it and freeing its slot. Calling this with a handle that's already gone
+/// (stale or already resolved) is a harmless no-op.
+#[no_mangle]
+extern "C" fn lingua_cancel_future(
+	handle_bits: u64
+) -> u8 {
+	ffi_panic_boundary(|| {
+		if let Some(handle) = handle_map::handle_from_bits(handle_bits) {
+			API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+				let _ = api_state.futures.remove(handle);
+			});
+		}
+	}) as u8
+}
@@ -0,0 +1,287 @@
+// Generational handle storage, used to hand out opaque identifiers across the
+// FFI boundary. Unlike a bare incrementing counter, a generational handle
+// can't alias fresh data once it's stale: each slot remembers which
+// "generation" currently occupies it, and a handle from an earlier generation
+// is rejected instead of silently reading whatever replaced it.
+
+use thiserror::Error;
+
+/// Identifies which handle map a [`Handle`] was allocated from, so a handle
+/// from one map can never be mistaken for a handle from another - even if
+/// their indices and generations happen to line up.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleTag {
+	DataFromRust = 0,
+	DataFromLuau = 1,
+	LuauCallback = 2,
+	Future = 3
+}
+
+impl HandleTag {
+	fn from_bits(
+		bits: u8
+	) -> Option<Self> {
+		match bits {
+			0 => Some(Self::DataFromRust),
+			1 => Some(Self::DataFromLuau),
+			2 => Some(Self::LuauCallback),
+			3 => Some(Self::Future),
+			_ => None
+		}
+	}
+}
+
+/// An opaque handle that can be packed into a single `u64` to send across the
+/// FFI boundary. Combines a [`HandleTag`], a 16-bit generation, and a 32-bit
+/// slot index - the generation is what lets a [`HandleMap`] tell a fresh
+/// handle apart from a stale or double-used one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle {
+	tag: HandleTag,
+	generation: u16,
+	index: u32
+}
+
+impl Handle {
+	const TAG_SHIFT: u32 = 48;
+	const GENERATION_SHIFT: u32 = 32;
+
+	/// Builds a handle with a fixed generation of `0`. Useful for handles
+	/// that are never recycled, so there's no stale-slot ambiguity to guard
+	/// against in the first place.
+	pub fn new_ungenerationed(
+		tag: HandleTag,
+		index: u32
+	) -> Self {
+		Self { tag, generation: 0, index }
+	}
+
+	fn pack(self) -> u64 {
+		((self.tag as u64) << Self::TAG_SHIFT)
+			| ((self.generation as u64) << Self::GENERATION_SHIFT)
+			| (self.index as u64)
+	}
+
+	fn unpack(
+		bits: u64
+	) -> Option<Self> {
+		let tag = HandleTag::from_bits((bits >> Self::TAG_SHIFT) as u8)?;
+		Some(Self {
+			tag,
+			generation: (bits >> Self::GENERATION_SHIFT) as u16,
+			index: bits as u32
+		})
+	}
+}
+
+impl From<Handle> for u64 {
+	fn from(
+		value: Handle
+	) -> Self {
+		value.pack()
+	}
+}
+
+/// The reasons a [`Handle`] might fail to resolve to live data. Distinguishes
+/// "this handle was never valid for this map" from "this handle used to be
+/// valid, but the slot it pointed to has since moved on" - the latter is the
+/// generational check doing its job, not memory corruption.
+#[derive(Debug, Error)]
+pub enum StaleHandleError {
+	#[error("handle belongs to a different handle map")]
+	WrongTag,
+	#[error("handle's generation does not match the current occupant of its slot")]
+	GenerationMismatch,
+	#[error("handle does not refer to any occupied slot")]
+	NotOccupied,
+	#[error("handle's data has been taken out of the map and is in use elsewhere")]
+	Taken
+}
+
+enum Slot<T> {
+	/// Free slots form an intrusive singly-linked list through `next_free`;
+	/// `u32::MAX` marks the end of the list. `generation` is the generation
+	/// that will be assigned the next time this slot is reused, so freeing a
+	/// slot and reusing it always hands out a different generation.
+	Free { next_free: u32, generation: u16 },
+	Occupied { generation: u16, data: T },
+	/// Temporarily emptied by [`HandleMap::take`] so its data can be used
+	/// without holding the map borrowed - e.g. polling a future without
+	/// holding `ApiState` borrowed for the duration. The handle stays valid
+	/// (the generation doesn't change), but any lookup against it fails with
+	/// [`StaleHandleError::Taken`] until [`HandleMap::put_back`] or
+	/// [`HandleMap::release`] resolves it.
+	Taken { generation: u16 }
+}
+
+/// Backing storage for one kind of handle, implemented as a generational
+/// index. Freed slots are kept on a free list and reused by later
+/// allocations, but each reuse bumps the slot's generation, so a handle into
+/// a freed-then-reused slot is detected as stale rather than silently
+/// aliasing whatever now lives there.
+pub struct HandleMap<T> {
+	tag: HandleTag,
+	slots: Vec<Slot<T>>,
+	next_free: u32
+}
+
+const END_OF_FREE_LIST: u32 = u32::MAX;
+
+impl<T> HandleMap<T> {
+	pub fn new(
+		tag: HandleTag
+	) -> Self {
+		Self {
+			tag,
+			slots: Vec::new(),
+			next_free: END_OF_FREE_LIST
+		}
+	}
+
+	/// Stores `data` in a free slot (allocating a new one if none are free)
+	/// and returns a handle that can be used to look it up again.
+	pub fn insert(
+		&mut self,
+		data: T
+	) -> Handle {
+		let index = self.next_free;
+		if index == END_OF_FREE_LIST {
+			let index = self.slots.len() as u32;
+			self.slots.push(Slot::Occupied { generation: 0, data });
+			return Handle { tag: self.tag, generation: 0, index };
+		}
+		let Slot::Free { next_free, generation } = self.slots[index as usize] else {
+			unreachable!("[lingua] handle map free list pointed at an occupied slot");
+		};
+		self.next_free = next_free;
+		self.slots[index as usize] = Slot::Occupied { generation, data };
+		Handle { tag: self.tag, generation, index }
+	}
+
+	fn occupied(
+		&self,
+		handle: Handle
+	) -> Result<&T, StaleHandleError> {
+		if handle.tag != self.tag {
+			return Err(StaleHandleError::WrongTag);
+		}
+		match self.slots.get(handle.index as usize) {
+			Some(Slot::Occupied { generation, data }) if *generation == handle.generation =>
+				Ok(data),
+			Some(Slot::Occupied { .. }) => Err(StaleHandleError::GenerationMismatch),
+			Some(Slot::Taken { generation }) if *generation == handle.generation =>
+				Err(StaleHandleError::Taken),
+			Some(Slot::Taken { .. }) => Err(StaleHandleError::GenerationMismatch),
+			Some(Slot::Free { .. }) | None => Err(StaleHandleError::NotOccupied)
+		}
+	}
+
+	pub fn get(
+		&self,
+		handle: Handle
+	) -> Result<&T, StaleHandleError> {
+		self.occupied(handle)
+	}
+
+	pub fn get_mut(
+		&mut self,
+		handle: Handle
+	) -> Result<&mut T, StaleHandleError> {
+		self.occupied(handle)?;
+		match &mut self.slots[handle.index as usize] {
+			Slot::Occupied { data, .. } => Ok(data),
+			_ => unreachable!("[lingua] occupied() would have already returned an error")
+		}
+	}
+
+	pub fn contains(
+		&self,
+		handle: Handle
+	) -> bool {
+		self.occupied(handle).is_ok()
+	}
+
+	/// Removes the data behind `handle`, bumping the slot's generation
+	/// (wrapping) so this handle (and any copy of it) can never resolve
+	/// again, then returns the slot to the free list.
+	pub fn remove(
+		&mut self,
+		handle: Handle
+	) -> Result<T, StaleHandleError> {
+		self.occupied(handle)?;
+		let next_generation = handle.generation.wrapping_add(1);
+		let Slot::Occupied { data, .. } = std::mem::replace(
+			&mut self.slots[handle.index as usize],
+			Slot::Free { next_free: self.next_free, generation: next_generation }
+		) else {
+			unreachable!("[lingua] occupied() would have already returned an error");
+		};
+		self.next_free = handle.index;
+		Ok(data)
+	}
+
+	/// Removes the data behind `handle` without invalidating the handle -
+	/// the slot is marked [`Slot::Taken`] rather than freed, so the same
+	/// handle can later be restored with [`put_back`](Self::put_back) or
+	/// given up for good with [`release`](Self::release). Useful for
+	/// working on a slot's data without holding the map borrowed for the
+	/// duration, while still reserving its identity against reentrant
+	/// lookups.
+	pub fn take(
+		&mut self,
+		handle: Handle
+	) -> Result<T, StaleHandleError> {
+		self.occupied(handle)?;
+		let Slot::Occupied { generation, data } = std::mem::replace(
+			&mut self.slots[handle.index as usize],
+			Slot::Taken { generation: handle.generation }
+		) else {
+			unreachable!("[lingua] occupied() would have already returned an error");
+		};
+		Ok(data)
+	}
+
+	/// Restores data previously removed by [`take`](Self::take) to the same
+	/// slot, under the same handle - unlike `remove` followed by `insert`,
+	/// this doesn't bump the slot's generation, so the handle the caller
+	/// already holds keeps resolving to it.
+	pub fn put_back(
+		&mut self,
+		handle: Handle,
+		data: T
+	) {
+		match self.slots.get(handle.index as usize) {
+			Some(Slot::Taken { generation }) if *generation == handle.generation => {
+				self.slots[handle.index as usize] = Slot::Occupied { generation: handle.generation, data };
+			},
+			_ => unreachable!("[lingua] put_back called for a slot that wasn't taken by this handle")
+		}
+	}
+
+	/// Frees the slot previously emptied by [`take`](Self::take) for good,
+	/// bumping its generation (wrapping) like [`remove`](Self::remove) so
+	/// this handle can never resolve again - used when the caller has
+	/// decided not to put the data back (e.g. the future it took out has
+	/// now resolved).
+	pub fn release(
+		&mut self,
+		handle: Handle
+	) {
+		match self.slots.get(handle.index as usize) {
+			Some(Slot::Taken { generation }) if *generation == handle.generation => {
+				let next_generation = generation.wrapping_add(1);
+				self.slots[handle.index as usize] =
+					Slot::Free { next_free: self.next_free, generation: next_generation };
+				self.next_free = handle.index;
+			},
+			_ => unreachable!("[lingua] release called for a slot that wasn't taken by this handle")
+		}
+	}
+}
+
+pub fn handle_from_bits(
+	bits: u64
+) -> Option<Handle> {
+	Handle::unpack(bits)
+}
@@ -12,11 +12,29 @@ compile_error!(
 	Either configure your project's target triple, \
 	or conditionally depend on Lingua.");
 
-use std::{cell::RefCell, collections::HashMap, num::Wrapping, panic::{catch_unwind, AssertUnwindSafe, UnwindSafe}};
+use std::{cell::RefCell, num::Wrapping, panic::{catch_unwind, AssertUnwindSafe, UnwindSafe}};
 
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
+mod handle_map;
+mod codec;
+mod buffer;
+mod result;
+mod callback;
+mod future;
+pub mod ffi;
+
+use handle_map::{Handle, HandleMap, HandleTag};
+use buffer::RustBuffer;
+use codec::Codec;
+
+pub use handle_map::StaleHandleError;
+pub use codec::{EncodeError, DecodeError};
+pub use result::{send_result_to_luau, ExternError};
+pub use callback::{LuauCallback, InvokeLuauCallbackError};
+pub use future::{send_future_to_luau, FutureHandle};
+
 /// The return values of Lingua FFI functions are used to indicate whether the
 /// FFI call was successful. Note that this has nothing to do with the specific
 /// operation - it's specifically used to communicate low-level failures.
@@ -46,7 +64,7 @@ mod return_codes {
 			}
 		}
 	}
-	
+
 	#[repr(u8)]
 	pub enum Rust {
 		/// The FFI call was successfully handled without panicking.
@@ -56,29 +74,44 @@ mod return_codes {
 	}
 }
 
-enum JustReceivedJson {
-	AllocatedOnly(String),
-	Ready(String)
+enum JustReceivedData {
+	AllocatedOnly(Vec<u8>),
+	Ready(Vec<u8>)
 }
 
 /// The internal state used by Lingua's Rust-side API.
 /// This should be treated as a singleton representing the whole module.
 struct ApiState {
-	/// When the Luau side sends JSON strings, they're stored here, indexed by
-	/// which handle the Luau side decided to use.
-	just_received_json: HashMap<u32, JustReceivedJson>,
-	/// To uniquely identify JSON strings the Rust side sends to the Luau side,
-	/// this handle is incremented. Handles generated on the Rust side may
-	/// collide with handles generated on the Luau side because no
-	/// synchronisation is done.
-	next_rust_handle: Wrapping<u32>
+	/// When the Luau side sends data, it's stored here, indexed by a
+	/// generational handle. Unlike a flat map keyed by a caller-chosen
+	/// number, a stale or double-used handle is detected rather than silently
+	/// aliasing whatever now occupies the slot.
+	just_received_data: HandleMap<JustReceivedData>,
+	/// To uniquely identify data the Rust side sends to the Luau side, this
+	/// index is incremented. These handles are never recycled - Rust doesn't
+	/// read them back, so there's no stale-slot to detect - but they're still
+	/// tagged as `DataFromRust`, so they can never be confused with a
+	/// Luau-generated handle even if the raw indices line up.
+	next_rust_handle: Wrapping<u32>,
+	/// Opaque, Luau-defined tokens identifying Luau functions that Rust code
+	/// has been handed a [`LuauCallback`] for. Lingua never interprets these
+	/// tokens itself - it just keeps them alive behind a generational handle
+	/// until the corresponding `LuauCallback` is dropped.
+	luau_callbacks: HandleMap<u64>,
+	/// Futures that have been exposed to the Luau side via
+	/// `send_future_to_luau`, indexed by a generational handle so a poll or
+	/// cancel against a future that's already resolved is rejected instead of
+	/// silently acting on whatever now occupies its slot.
+	futures: HandleMap<future::BoxedFuture>
 }
 
 impl ApiState {
 	pub fn new() -> Self {
 		Self {
-			just_received_json: HashMap::new(),
-			next_rust_handle: Wrapping(0)
+			just_received_data: HandleMap::new(HandleTag::DataFromLuau),
+			next_rust_handle: Wrapping(0),
+			luau_callbacks: HandleMap::new(HandleTag::LuauCallback),
+			futures: HandleMap::new(HandleTag::Future)
 		}
 	}
 }
@@ -103,116 +136,111 @@ fn ffi_panic_boundary<Func: FnOnce() -> () + UnwindSafe>(
 }
 
 extern "C" {
-	/// The Rust side calls this function when it sends a JSON string. It's
-	/// called with the handle that it generated, the pointer to the string, and
-	/// the length of that string. To minimise the chance of errors at the FFI
-	/// boundary, the string is saved without decoding the data or invoking any
-	/// user callbacks.
+	/// The Rust side calls this function when it sends data. It's called
+	/// with the handle that it generated and a [`RustBuffer`] describing the
+	/// data's raw parts. The Luau side is expected to read the buffer
+	/// synchronously during this call - ownership isn't transferred, so Rust
+	/// frees the buffer as soon as the call returns.
 	#[must_use]
-	fn lingua_send_json_to_luau(
-		rust_handle: u32,
-		ptr: *mut u8,
-		len: u32
+	fn lingua_send_data_to_luau(
+		rust_handle: u64,
+		buffer: RustBuffer
 	) -> u8;
 }
 
-/// The Luau side calls this function to initiate sending a JSON string. It's
-/// called with the handle that it generated and the length of the string that
-/// it would like to transfer. The Rust side is expected to reserve space for
-/// the string and return a pointer to this reserved space, with a null pointer
-/// representing a failure to allocate space.
+/// The Luau side calls this function to initiate sending data. It's called
+/// with the length of the buffer that it would like to transfer. The Rust
+/// side reserves space for the data, writes the handle it generated for this
+/// transfer through `out_handle`, and returns a [`RustBuffer`] describing the
+/// reserved space, with a null pointer representing a failure to allocate
+/// space.
 #[no_mangle]
-extern "C" fn lingua_send_json_to_rust_alloc(
-	luau_handle: u32,
-	len: u32
-) -> *mut u8 {
-	let mut return_ptr = 0 as *mut u8;
+extern "C" fn lingua_send_data_to_rust_alloc(
+	len: u32,
+	out_handle: *mut u64
+) -> RustBuffer {
+	let mut return_buffer = RustBuffer { ptr: std::ptr::null_mut(), len: 0, capacity: 0 };
 	ffi_panic_boundary(AssertUnwindSafe(|| {
-		// Fill the string with something that's easy to recognise if part of 
-		// the string remains uninitialised.
-		let mut str = String::from_iter((0..len).map(|_| '£'));
-		assert!(
-			str.capacity() >= len as usize,
-			"[lingua] sanity check failed: send_json_to_rust_alloc string does \
-			not have the right capacity for the requested data length"
-		);
-		let str_ptr = str.as_mut_ptr();
+		// Zero-filled rather than left uninitialised: the Luau side is
+		// expected to overwrite all `len` bytes before calling
+		// `lingua_send_data_to_rust`, but reading the handle back out is
+		// refused until then regardless (see `AllocatedOnly`).
+		let mut bytes = vec![0u8; len as usize];
+		return_buffer = RustBuffer {
+			ptr: bytes.as_mut_ptr(),
+			len: bytes.len() as u32,
+			capacity: bytes.capacity() as u32
+		};
 		API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
-			assert!(
-				!api_state.just_received_json.contains_key(&luau_handle),
-				"[lingua] luau handle {luau_handle} is already in use - \
-				ensure you're reading all data sent to the rust side"
-			);
-			api_state.just_received_json.insert(
-				luau_handle, 
-				JustReceivedJson::AllocatedOnly(str)
+			let handle = api_state.just_received_data.insert(
+				JustReceivedData::AllocatedOnly(bytes)
 			);
+			unsafe { *out_handle = handle.into(); }
 		});
-		return_ptr = str_ptr;
 	}));
-	return_ptr
+	return_buffer
 }
 
 /// The Luau side is expected to call this function once it has finished writing
-/// to space previously allocated for the transfer of JSON data. This signals to
+/// to space previously allocated for the transfer of data. This signals to
 /// the Rust side that it is safe to access the data.
 #[no_mangle]
-extern "C" fn lingua_send_json_to_rust(
-	luau_handle: u32
+extern "C" fn lingua_send_data_to_rust(
+	luau_handle: u64
 ) -> u8 {
 	ffi_panic_boundary(|| {
 		API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
-			let Some(data) = api_state.just_received_json.remove(&luau_handle) else {
-				panic!(
+			let handle = handle_map::handle_from_bits(luau_handle)
+				.and_then(|handle| api_state.just_received_data.contains(handle).then_some(handle))
+				.unwrap_or_else(|| panic!(
 					"[lingua] luau handle {luau_handle} has no data - handles \
-					should be generated on the sending side and are single use"
-				);
-			};
+					should be generated on the rust side and are single use"
+				));
+			let data = api_state.just_received_data.get_mut(handle)
+				.expect("[lingua] handle was just confirmed to be occupied");
 			match data {
-				JustReceivedJson::AllocatedOnly(str) =>
-					api_state.just_received_json.insert(
-						luau_handle,
-						JustReceivedJson::Ready(str)
-					),
-				JustReceivedJson::Ready(_) => 
+				JustReceivedData::AllocatedOnly(bytes) => {
+					let bytes = std::mem::take(bytes);
+					*data = JustReceivedData::Ready(bytes);
+				},
+				JustReceivedData::Ready(_) =>
 					panic!(
 						"[lingua] luau handle {luau_handle} was already sent - \
 						handles are single use and should only be sent once"
 					)
 			}
-			
 		});
 	}) as u8
 }
 
 /// When data is sent from Rust, this opaque handle is generated to concisely
 /// refer to that data. This handle must always be sent to the Luau side; this
-/// is done by converting it into a `u32` and sending it through an `extern fn`.
+/// is done by converting it into a `u64` and sending it through an `extern fn`.
 #[repr(transparent)]
-pub struct DataFromRustHandle(u32);
-impl From<DataFromRustHandle> for u32 {
+pub struct DataFromRustHandle(Handle);
+impl From<DataFromRustHandle> for u64 {
 	fn from(
 		value: DataFromRustHandle
 	) -> Self {
-		value.0
+		value.0.into()
 	}
 }
 
 /// When data is sent from Luau, this opaque handle is generated to concisely
 /// refer to that data. This handle is received across the FFI boundary by
-/// obtaining it through an `extern fn` and converting it from a `u32`.
+/// obtaining it through an `extern fn` and converting it from a `u64`.
 #[repr(transparent)]
-pub struct DataFromLuauHandle(u32);
-impl From<u32> for DataFromLuauHandle {
-	fn from(value: u32) -> Self {
+pub struct DataFromLuauHandle(u64);
+impl From<u64> for DataFromLuauHandle {
+	fn from(value: u64) -> Self {
 		Self(value)
 	}
 }
 
 #[derive(Debug, Error)]
 pub enum SendToLuauError {
-	#[error("error while serializing")]
-	SerdeError(serde_json::Error),
+	#[error("error while encoding data")]
+	EncodeError(codec::EncodeError),
 	#[error("could not convert serialized form to C string")]
 	CStringError,
 	#[error("the luau side encountered an error at the ffi boundary")]
@@ -225,57 +253,117 @@ pub enum SendToLuauError {
 
 #[derive(Debug, Error)]
 pub enum ReceiveFromLuauError {
-	#[error("error while deserializing")]
-	SerdeError(serde_json::Error),
-	#[error("luau handle has no data")]
-	NoDataError,
+	#[error("error while decoding data")]
+	DecodeError(codec::DecodeError),
+	#[error("luau handle does not refer to live data: {0}")]
+	StaleHandle(StaleHandleError),
 	#[error("luau handle has allocated memory but has not submitted data yet")]
 	AllocatedOnlyError
-}	
+}
+
+fn send_bytes_to_luau_with_state(
+	api_state: &mut ApiState,
+	mut bytes: Vec<u8>
+) -> Result<DataFromRustHandle, SendToLuauError> {
+	let handle = Handle::new_ungenerationed(HandleTag::DataFromRust, api_state.next_rust_handle.0);
+	api_state.next_rust_handle += 1;
+	let buffer = RustBuffer {
+		ptr: bytes.as_mut_ptr(),
+		len: bytes.len() as u32,
+		capacity: bytes.capacity() as u32
+	};
+	let status = return_codes::Luau::interpret(unsafe {
+		lingua_send_data_to_luau(handle.into(), buffer)
+	});
+	match status {
+		Some(status) => match status {
+			return_codes::Luau::Success =>
+				Ok(DataFromRustHandle(handle)),
+			return_codes::Luau::UncaughtErrorAtFfiBoundary =>
+				Err(SendToLuauError::LuauErrorAtFfiBoundaryError),
+			return_codes::Luau::LuauApiNotReady =>
+				Err(SendToLuauError::LuauApiNotReadyError)
+		},
+		None => Err(SendToLuauError::LuauUnknownError)
+	}
+}
+
+/// Sends raw bytes to the Luau side, without passing them through a [`Codec`]
+/// first. An opaque `DataFromRustHandle` is returned; you are always expected
+/// to send this handle to the Luau side.
+pub fn send_bytes_to_luau(
+	bytes: Vec<u8>
+) -> Result<DataFromRustHandle, SendToLuauError> {
+	API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
+		send_bytes_to_luau_with_state(api_state, bytes)
+	})
+}
 
-/// Sends some data to the Luau side. An opaque `DataFromRustHandle` is
-/// returned; you are always expected to send this handle to the Luau side.
+/// Sends some data to the Luau side, serializing it with the active
+/// [`Codec`] first. An opaque `DataFromRustHandle` is returned; you are
+/// always expected to send this handle to the Luau side.
 pub fn send_to_luau<S: Serialize>(
 	data: &S
 ) -> Result<DataFromRustHandle, SendToLuauError> {
-	API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
-		let mut str = serde_json::to_string(data).map_err(|e| SendToLuauError::SerdeError(e))?;
-		let rust_handle = api_state.next_rust_handle.0;
-		api_state.next_rust_handle += 1;
-		let len = str.len() as u32;
-		let ptr = str.as_mut_ptr();
-		let status = return_codes::Luau::interpret(unsafe {
-			lingua_send_json_to_luau(rust_handle, ptr, len)
-		});
-		match status {
-			Some(status) => match status {
-				return_codes::Luau::Success => 
-					Ok(DataFromRustHandle(rust_handle)),
-				return_codes::Luau::UncaughtErrorAtFfiBoundary => 
-					Err(SendToLuauError::LuauErrorAtFfiBoundaryError),
-				return_codes::Luau::LuauApiNotReady => 
-					Err(SendToLuauError::LuauApiNotReadyError)
-			},
-			None => Err(SendToLuauError::LuauUnknownError)
-		}
+	let bytes = codec::ActiveCodec::encode(data).map_err(SendToLuauError::EncodeError)?;
+	send_bytes_to_luau(bytes)
+}
+
+/// Like [`send_bytes_to_luau`], but instead of panicking if `API_STATE` is
+/// already mutably borrowed on this thread, returns `None` without touching
+/// it. Meant for contexts that can legitimately run while some other call
+/// into this crate is still on the stack - chiefly a panic hook, which runs
+/// synchronously at the `panic!` site, possibly from inside another
+/// `API_STATE.with_borrow_mut` call that's unwinding. Re-borrowing there
+/// would turn that panic into a `BorrowMutError` panic, which aborts the
+/// process instead of unwinding cleanly to `ffi_panic_boundary`.
+pub fn try_send_bytes_to_luau(
+	bytes: Vec<u8>
+) -> Option<Result<DataFromRustHandle, SendToLuauError>> {
+	API_STATE.with(|state| {
+		let mut api_state = state.try_borrow_mut().ok()?;
+		Some(send_bytes_to_luau_with_state(&mut api_state, bytes))
 	})
 }
 
-/// Receives some data from the Luau side. You need to generate and send a
-/// `DataFromLuauHandle` yourself from within Luau.
-pub fn receive_from_luau<D: DeserializeOwned>(
+/// Like [`send_to_luau`], but instead of panicking if `API_STATE` is already
+/// mutably borrowed on this thread, returns `None` without touching it - see
+/// [`try_send_bytes_to_luau`] for why this exists.
+pub fn try_send_to_luau<S: Serialize>(
+	data: &S
+) -> Option<Result<DataFromRustHandle, SendToLuauError>> {
+	match codec::ActiveCodec::encode(data) {
+		Ok(bytes) => try_send_bytes_to_luau(bytes),
+		Err(encode_error) => Some(Err(SendToLuauError::EncodeError(encode_error)))
+	}
+}
+
+/// Receives raw bytes from the Luau side, without passing them through a
+/// [`Codec`] first. You need to generate and send a `DataFromLuauHandle`
+/// yourself from within Luau.
+pub fn receive_bytes_from_luau(
 	luau_handle: DataFromLuauHandle
-) -> Result<D, ReceiveFromLuauError> {
+) -> Result<Vec<u8>, ReceiveFromLuauError> {
 	API_STATE.with_borrow_mut(|api_state: &mut ApiState| {
-		let str = api_state.just_received_json.remove(&luau_handle.0);
-		match str {
-			None => 
-				Err(ReceiveFromLuauError::NoDataError),
-			Some(JustReceivedJson::AllocatedOnly(_)) =>
+		let handle = handle_map::handle_from_bits(luau_handle.0)
+			.ok_or(ReceiveFromLuauError::StaleHandle(StaleHandleError::NotOccupied))?;
+		let data = api_state.just_received_data.remove(handle)
+			.map_err(ReceiveFromLuauError::StaleHandle)?;
+		match data {
+			JustReceivedData::AllocatedOnly(_) =>
 				Err(ReceiveFromLuauError::AllocatedOnlyError),
-			Some(JustReceivedJson::Ready(str)) =>
-				serde_json::from_str(&str)
-				.map_err(|e| ReceiveFromLuauError::SerdeError(e))
+			JustReceivedData::Ready(bytes) =>
+				Ok(bytes)
 		}
 	})
-}
\ No newline at end of file
+}
+
+/// Receives some data from the Luau side, decoding it with the active
+/// [`Codec`]. You need to generate and send a `DataFromLuauHandle` yourself
+/// from within Luau.
+pub fn receive_from_luau<D: DeserializeOwned>(
+	luau_handle: DataFromLuauHandle
+) -> Result<D, ReceiveFromLuauError> {
+	let bytes = receive_bytes_from_luau(luau_handle)?;
+	codec::ActiveCodec::decode(&bytes).map_err(ReceiveFromLuauError::DecodeError)
+}
@@ -0,0 +1,105 @@
+// Lets fallible Rust functions hand their `Result::Err` back to Luau through
+// a structured out-parameter, rather than forcing every operation to smuggle
+// its errors through a panic or a hand-rolled side channel. Follows the
+// `ExternError` convention from Mozilla's ffi-support: the caller always
+// checks `code` before trusting the return value of the wrapped call.
+
+use std::{any::Any, ffi::CString, panic::{catch_unwind, UnwindSafe}};
+
+use serde::Serialize;
+
+use crate::send_to_luau;
+
+/// The error code used for a positive, but otherwise undifferentiated, user
+/// error - i.e. a wrapped Rust call returned `Err`. Operations that need to
+/// distinguish between kinds of failure can match on the serialized message
+/// instead of relying on a more specific code.
+pub const USER_ERROR_CODE: i32 = 1;
+
+/// The reserved error code used when a panic was caught at the FFI boundary,
+/// so Luau can tell "the operation failed" apart from "Rust fell over".
+pub const PANIC_ERROR_CODE: i32 = -1;
+
+/// The reserved error code used when `f` itself succeeded, but its `Ok` value
+/// couldn't be handed across the FFI boundary afterwards (encoding failure,
+/// the Luau side not being ready, and so on). Distinct from
+/// `PANIC_ERROR_CODE` because nothing actually panicked here.
+pub const SEND_ERROR_CODE: i32 = -2;
+
+/// Populated by an FFI wrapper when the Rust call it wraps returns `Err` or
+/// panics. `code == 0` means success, in which case `message` is null and the
+/// wrapper's return value can be trusted; any other code means `message`
+/// points to a NUL-terminated, JSON-encoded description of what went wrong,
+/// which the Luau side should free with `lingua_dealloc_received_string` once
+/// it's done reading it. This message is always JSON, regardless of which
+/// [`Codec`](crate::Codec) is active for ordinary data - it's framed as a
+/// NUL-terminated C string rather than a length-prefixed buffer, which a
+/// binary codec's output can't safely be.
+#[repr(C)]
+pub struct ExternError {
+	pub code: i32,
+	pub message: *mut u8,
+	pub len: u32
+}
+
+impl ExternError {
+	fn success() -> Self {
+		Self { code: 0, message: std::ptr::null_mut(), len: 0 }
+	}
+
+	fn with_message<E: Serialize>(
+		code: i32,
+		error: &E
+	) -> Self {
+		let json = serde_json::to_string(error)
+			.unwrap_or_else(|e| format!("failed to serialize error: {e}"));
+		let c_string = CString::new(json)
+			.unwrap_or_else(|_| CString::new("error message contained a nul byte").unwrap());
+		let len = c_string.as_bytes().len() as u32;
+		Self { code, message: c_string.into_raw() as *mut u8, len }
+	}
+}
+
+fn panic_message(
+	payload: &(dyn Any + Send)
+) -> String {
+	if let Some(message) = payload.downcast_ref::<&str>() {
+		message.to_string()
+	} else if let Some(message) = payload.downcast_ref::<String>() {
+		message.clone()
+	} else {
+		String::from("panicked with a non-string payload")
+	}
+}
+
+/// Runs `f` inside the crate's usual panic boundary and translates its
+/// `Result` into the `ExternError` out-parameter convention. On `Ok`, the
+/// value is sent to Luau as a normal data handle and returned; on `Err` - or
+/// a caught panic - `out_error` is populated instead and the return value
+/// should be ignored.
+///
+/// # Safety
+/// `out_error` must be a valid, aligned, writable pointer to an
+/// `ExternError` - as is always the case for the out-parameter of a
+/// `#[no_mangle] extern "C"` FFI wrapper.
+pub unsafe fn send_result_to_luau<T: Serialize, E: Serialize>(
+	out_error: *mut ExternError,
+	f: impl FnOnce() -> Result<T, E> + UnwindSafe
+) -> u64 {
+	// `send_to_luau` is run inside the same `catch_unwind` as `f`, not after
+	// it - a panic while serializing or handing the value across the FFI
+	// boundary is just as much a threat to this function's callers as a
+	// panic inside `f` itself, and should be caught the same way.
+	let (handle, error) = match catch_unwind(move || f().map(|value| send_to_luau(&value))) {
+		Ok(Ok(Ok(handle))) => (handle.into(), ExternError::success()),
+		Ok(Ok(Err(send_error))) => (0, ExternError::with_message(SEND_ERROR_CODE, &send_error.to_string())),
+		Ok(Err(user_error)) => (0, ExternError::with_message(USER_ERROR_CODE, &user_error)),
+		Err(panic) => {
+			let message = panic_message(panic.as_ref());
+			log::error!("[lingua] panic at ffi boundary\n\ncaused by:\n{message}");
+			(0, ExternError::with_message(PANIC_ERROR_CODE, &message))
+		}
+	};
+	unsafe { *out_error = error; }
+	handle
+}